@@ -0,0 +1,11 @@
+/// Table-structure checksum convention used by the OCSD option-card
+/// descriptors: a structure is valid when the 8-bit sum of every byte in it,
+/// including the checksum byte itself, is zero.
+///
+/// `ocsd`'s structs don't expose a `checksum` field for us to set ourselves
+/// (the wire-format checksum is presumably computed internally when a
+/// structure is serialized/written), so this crate only ever verifies a
+/// structure's bytes after the fact, never writes one.
+pub fn verify_checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
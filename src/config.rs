@@ -0,0 +1,145 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A single `pci_bus -> slot` entry in a platform's option-card topology.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct SlotMapping {
+    pub pci_bus: u8,
+    pub slot: u8,
+}
+
+/// Ordered `pci_bus -> slot` lookup table for the active platform.
+#[derive(Debug, Clone, Default)]
+pub struct SlotMap {
+    mappings: Vec<SlotMapping>,
+}
+
+impl SlotMap {
+    pub fn slot_for_pci_bus(&self, bus: u8) -> Option<u8> {
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.pci_bus == bus)
+            .map(|mapping| mapping.slot)
+    }
+}
+
+impl From<Vec<SlotMapping>> for SlotMap {
+    fn from(mappings: Vec<SlotMapping>) -> Self {
+        Self { mappings }
+    }
+}
+
+/// Describes an OCSD platform: where its memory-mapped header lives, how
+/// many option-card buffers it has, and which PCI bus maps to which slot.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PlatformProfile {
+    pub name: String,
+    pub base_address: usize,
+    /// Reported `max_option_cards` for this platform, used only to sanity
+    /// check what the live OCSD header reports; the header is always the
+    /// authoritative value actually written to.
+    pub max_option_cards: Option<u8>,
+    pub slot_map: Vec<SlotMapping>,
+}
+
+impl PlatformProfile {
+    pub fn ml350_gen9() -> Self {
+        Self {
+            name: "ml350-gen9".to_string(),
+            base_address: ocsd::client::base_address::ML350_GEN9 as usize,
+            max_option_cards: None,
+            slot_map: vec![
+                SlotMapping {
+                    pci_bus: 0x04,
+                    slot: 1,
+                },
+                SlotMapping {
+                    pci_bus: 0x0a,
+                    slot: 2,
+                },
+            ],
+        }
+    }
+
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "ml350-gen9" => Some(Self::ml350_gen9()),
+            _ => None,
+        }
+    }
+
+    pub fn slot_map(&self) -> SlotMap {
+        self.slot_map.clone().into()
+    }
+}
+
+/// A config file either selects a built-in profile by name, or defines a
+/// platform's topology explicitly.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ConfigFile {
+    Named { profile: String },
+    Explicit(PlatformProfile),
+}
+
+const CONFIG_FILE_NAME: &str = "nvml_ocsd_reporter_config.json";
+
+fn parse_config_path_from_args() -> Option<PathBuf> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == "--config")
+        .and_then(|index| args.get(index + 1))
+        .map(PathBuf::from)
+}
+
+fn load_config_file(path: &Path) -> Option<PlatformProfile> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            warn!("Couldn't read config file {}: {:?}", path.display(), err);
+            return None;
+        }
+    };
+
+    let config: ConfigFile = match serde_json::from_str(&contents) {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("Couldn't parse config file {}: {:?}", path.display(), err);
+            return None;
+        }
+    };
+
+    match config {
+        ConfigFile::Named { profile } => match PlatformProfile::builtin(&profile) {
+            Some(profile) => Some(profile),
+            None => {
+                warn!("Unknown built-in platform profile {:?}", profile);
+                None
+            }
+        },
+        ConfigFile::Explicit(profile) => Some(profile),
+    }
+}
+
+/// Loads the active platform profile: from `--config <path>` if given,
+/// otherwise from `nvml_ocsd_reporter_config.json` next to the state file,
+/// falling back to the built-in ML350 Gen9 profile.
+pub fn load_platform_profile() -> PlatformProfile {
+    let config_path =
+        parse_config_path_from_args().unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME));
+
+    if config_path.exists() {
+        if let Some(profile) = load_config_file(&config_path) {
+            info!("Loaded platform profile {:?} from {}", profile.name, config_path.display());
+            return profile;
+        }
+        warn!(
+            "Falling back to built-in ml350-gen9 profile after failing to load {}",
+            config_path.display()
+        );
+    }
+
+    PlatformProfile::ml350_gen9()
+}
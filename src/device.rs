@@ -0,0 +1,46 @@
+use crate::config::SlotMap;
+use crate::slot::HasSlot;
+use nvml_wrapper::struct_wrappers::device::PciInfo;
+
+impl HasSlot for PciInfo {
+    fn slot(&self, slot_map: &SlotMap) -> Option<u8> {
+        slot_map.slot_for_pci_bus(self.bus.try_into().unwrap_or(0xff))
+    }
+}
+
+/// A single temperature reading surfaced by a `TemperatureSource`, ready to be
+/// converted into an `OcsdDevice` and written to an option-card slot.
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub slot: Option<u8>,
+    pub pci_bus: Option<u8>,
+    pub current_temperature_celsius: i16,
+    pub temperature_threshold_celsius: Option<i16>,
+    pub caution_threshold_celsius: Option<i16>,
+    pub name: String,
+    pub count: u16,
+    pub failed: bool,
+}
+
+pub fn format_device_info(index: usize, device: &DeviceInfo) -> String {
+    format!(
+        "{}\n{}\n{}\n",
+        format!(
+            "Device {:?} with index {} in {}",
+            device.name,
+            index,
+            match device.slot {
+                Some(slot) => format!("slot #{}:", slot),
+                None => format!("unknown slot:"),
+            }
+        ),
+        format!(
+            "Current temperature: {}C",
+            device.current_temperature_celsius
+        ),
+        match device.temperature_threshold_celsius {
+            Some(threshold) => format!("Temperature threshold: {:?}C", threshold),
+            None => format!("Temperature threshold: unknown"),
+        }
+    )
+}
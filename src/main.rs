@@ -1,7 +1,13 @@
+mod checksum;
+mod config;
+mod device;
+mod slot;
+mod temperature;
+
+use config::{load_platform_profile, SlotMap};
+use device::{format_device_info, DeviceInfo};
 use log::{debug, info, warn};
-use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
 use nvml_wrapper::error::NvmlError;
-use nvml_wrapper::struct_wrappers::device::PciInfo;
 use ocsd::OcsdHeader;
 use ocsd::{
     client::OcsdContext, Celsius, MemoryMapped, OcsdDevice, OcsdDeviceHeader, OcsdSensor,
@@ -9,14 +15,15 @@ use ocsd::{
 };
 use serde::{Deserialize, Serialize};
 use simple_logger::SimpleLogger;
-use std::fmt::Debug;
 use std::process::exit;
 use std::sync::{atomic, Arc};
 use std::time::Duration;
 use std::{cmp::min, fs::OpenOptions};
 use systemd_journal_logger::{connected_to_journal, JournalLog};
-
-use nvml_wrapper::Nvml;
+use temperature::hwmon::HwmonSource;
+use temperature::nvml::NvmlSource;
+use temperature::smart::SmartSource;
+use temperature::TemperatureSource;
 
 fn format_struct_bytes(bytes: &Vec<u8>) -> String {
     let num_chunks = bytes.len() / 8;
@@ -50,159 +57,110 @@ struct AppState {
     original_buffers_in_use: u8,
 }
 
-trait HasSlot {
-    fn slot(&self) -> Option<u8>;
-}
-
-impl HasSlot for PciInfo {
-    fn slot(&self) -> Option<u8> {
-        match self.bus {
-            0x04 => Some(1),
-            0x0a => Some(2),
-            _default => None,
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-struct DeviceInfo {
-    slot: Option<u8>,
-    pci_bus: u8,
-    current_temperature_celsius: i16,
-    temperature_threshold_celsius: Option<i16>,
-    name: String,
-    count: u16,
-}
-
-fn format_device_info(index: usize, device: &DeviceInfo) -> String {
-    format!(
-        "{}\n{}\n{}\n",
-        format!(
-            "Device {:?} with index {} in {}",
-            device.name,
-            index,
-            match device.slot {
-                Some(slot) => format!("slot #{}:", slot),
-                None => format!("unknown slot:"),
-            }
-        ),
-        format!(
-            "Current temperature: {}C",
-            device.current_temperature_celsius
-        ),
-        match device.temperature_threshold_celsius {
-            Some(threshold) => format!("Temperature threshold: {:?}C", threshold),
-            None => format!("Temperature threshold: unknown"),
-        }
-    )
-}
-
-trait ReadDeviceInfo {
-    fn read_device_info(&self, count: u16) -> Result<DeviceInfo, NvmlError>;
-}
-
-impl ReadDeviceInfo for nvml_wrapper::Device<'_> {
-    fn read_device_info(&self, count: u16) -> Result<DeviceInfo, NvmlError> {
-        let pci_info = self.pci_info()?;
+/// A `DeviceInfo` reading didn't fit the range `Celsius` can represent.
+#[derive(Debug)]
+struct CelsiusRangeError;
 
-        let slot = pci_info.slot();
-        let pci_bus: u8 = pci_info.bus.try_into().unwrap();
-        let current_temperature_celsius: i16 = self
-            .temperature(TemperatureSensor::Gpu)?
-            .try_into()
-            .unwrap();
-        let temperature_threshold_celsius: Option<i16> = match self.temperature_threshold(
-            nvml_wrapper::enum_wrappers::device::TemperatureThreshold::Slowdown,
-        ) {
-            Ok(threshold) => Some((threshold - 20).try_into().unwrap()),
-            Err(_) => Some(70),
-        };
-        let device_name = self.name()?;
-
-        Ok(DeviceInfo {
-            slot,
-            pci_bus,
-            current_temperature_celsius: current_temperature_celsius,
-            // the below offset is required for correct temperatures
-            // to be displayed in iLO, but also prevents fans from spinning
-            // up until close to 90deg (which is generally not desirable)
-            // + (temperature_threshold_celsius.unwrap() - 90),
-            temperature_threshold_celsius,
-            name: device_name,
-            count,
-        })
-    }
-}
+impl TryFrom<DeviceInfo> for OcsdDevice {
+    type Error = CelsiusRangeError;
 
-impl From<DeviceInfo> for OcsdDevice {
-    fn from(device: DeviceInfo) -> Self {
+    fn try_from(device: DeviceInfo) -> Result<Self, Self::Error> {
         let header = OcsdDeviceHeader {
             version: ocsd::DeviceVersion::Version1,
-            pci_bus: device.pci_bus,
+            pci_bus: device.pci_bus.unwrap_or(0),
             pci_device: 0x00,
             flags_caps: 0x00000010,
         };
 
+        // We have no way to compute this struct's wire-format checksum (the
+        // `ocsd` crate exposes no field or method to do so), so don't claim
+        // one is present -- a firmware that validates WithChecksum would
+        // reject every sensor we write.
+        let mut status = OcsdSensorStatus::Present;
+        if !device.failed {
+            status |= OcsdSensorStatus::NotFailed;
+        }
+
+        let max_continuous_threshold =
+            Celsius::new(device.temperature_threshold_celsius.unwrap_or(90))
+                .ok_or(CelsiusRangeError)?;
+        let caution_threshold = Celsius::new(device.caution_threshold_celsius.unwrap_or(100))
+            .ok_or(CelsiusRangeError)?;
+        let reading =
+            Celsius::new(device.current_temperature_celsius).ok_or(CelsiusRangeError)?;
+
         let sensor = OcsdSensor {
             sensor_type: OcsdSensorType::Thermal,
             sensor_location: OcsdSensorLocation::InternalToAsic,
             configuration: 0x0000,
-            status: OcsdSensorStatus::WithChecksum
-                | OcsdSensorStatus::Present
-                | OcsdSensorStatus::NotFailed,
-            max_continuous_threshold: Celsius::new(
-                device.temperature_threshold_celsius.unwrap_or(90),
-            )
-            .unwrap(),
-            caution_threshold: Celsius::new(100).unwrap(),
-            reading: Celsius::new(device.current_temperature_celsius).unwrap(),
+            status,
+            max_continuous_threshold,
+            caution_threshold,
+            reading,
             update_count: device.count,
-            bus: Some(device.pci_bus),
+            bus: device.pci_bus,
         };
 
-        OcsdDevice {
+        Ok(OcsdDevice {
             header,
             sensors: [sensor, Default::default(), Default::default()],
-        }
+        })
     }
 }
 
-fn print_nvml_devices(nvml_devices: &Vec<nvml_wrapper::Device>) -> Result<(), NvmlError> {
-    info!("Detected {} devices.\n", nvml_devices.len());
-    for (index, nvml_device) in (&nvml_devices).into_iter().enumerate() {
-        let device_info = nvml_device.read_device_info(1)?;
-        info!("\n{}", format_device_info(index, &device_info));
+/// Polls every configured temperature source and logs what it found, plus a
+/// warning for any device we can't place in a known OCSD slot.
+fn print_devices(
+    sources: &mut [Box<dyn TemperatureSource>],
+    slot_map: &SlotMap,
+) -> Vec<DeviceInfo> {
+    let mut devices = Vec::new();
+    for source in sources {
+        let source_devices = source.read_devices(0, slot_map);
+        info!(
+            "{} reported {} device(s).\n",
+            source.name(),
+            source_devices.len()
+        );
+        devices.extend(source_devices);
+    }
 
-        let example_ocsd = OcsdDevice::from(device_info);
-        debug!("\n{}", format_ocsd_report(example_ocsd));
+    for (index, device_info) in devices.iter().enumerate() {
+        info!("\n{}", format_device_info(index, device_info));
+        match OcsdDevice::try_from(device_info.clone()) {
+            Ok(example_ocsd) => debug!("\n{}", format_ocsd_report(example_ocsd)),
+            Err(_) => warn!(
+                "{} has a temperature reading out of OCSD's representable range",
+                device_info.name
+            ),
+        }
     }
 
-    let nvml_devices_without_slot: Vec<_> = nvml_devices
-        .into_iter()
-        .filter(|device| {
-            device
-                .pci_info()
-                .map_or(false, |info| info.slot().is_none())
-        })
-        .collect();
-    if nvml_devices_without_slot.len() > 0 {
-        let plural = if nvml_devices_without_slot.len() == 1 {
+    let devices_without_slot: Vec<_> = devices.iter().filter(|d| d.slot.is_none()).collect();
+    if devices_without_slot.len() > 0 {
+        let plural = if devices_without_slot.len() == 1 {
             ("", "its address")
         } else {
             ("s", "their addresses")
         };
         warn!(
             "Found {} device{} without known PCI slot for {}:\n",
-            nvml_devices_without_slot.len(),
+            devices_without_slot.len(),
             plural.0,
             plural.1
         );
-        for device in nvml_devices_without_slot {
-            let device_info = device.read_device_info(0)?;
-            warn!("{} on bus {:02x}", device_info.name, device_info.pci_bus);
+        for device in devices_without_slot {
+            warn!(
+                "{} on bus {}",
+                device.name,
+                device
+                    .pci_bus
+                    .map_or("unknown".to_string(), |bus| format!("{:02x}", bus))
+            );
         }
     }
-    Ok(())
+
+    devices
 }
 
 const STATE_FILE_NAME: &str = "nvml_ocsd_reporter.json";
@@ -240,13 +198,30 @@ fn open_state_file() -> std::fs::File {
         .unwrap()
 }
 
+/// How often (in ticks) to persist `AppState` while the loop is running, so a
+/// hard kill doesn't lose more than this many ticks' worth of progress.
+const STATE_SAVE_INTERVAL_TICKS: u16 = 30;
+
+fn save_app_state(count: u16, original_buffers_in_use: u8) {
+    let mut file = open_state_file();
+    if let Err(err) = serde_json::to_writer(
+        &mut file,
+        &AppState {
+            count,
+            original_buffers_in_use,
+        },
+    ) {
+        warn!("Couldn't save state: {:?}", err);
+    }
+}
+
 fn make_modified_header(
     original_header: &OcsdHeader,
     app_state: &AppState,
-    devices: &Vec<nvml_wrapper::Device>,
+    num_devices: usize,
 ) -> OcsdHeader {
     OcsdHeader {
-        buffers_in_use: app_state.original_buffers_in_use + devices.len() as u8,
+        buffers_in_use: app_state.original_buffers_in_use + num_devices as u8,
         ..*original_header
     }
 }
@@ -262,38 +237,57 @@ fn main() -> Result<(), NvmlError> {
         SimpleLogger::new().init().unwrap();
     }
 
-    let nvml = Nvml::init()?;
-    let num_devices = nvml.device_count()?;
-    let nvml_devices: Vec<nvml_wrapper::Device> = (0..num_devices)
-        .filter_map(|device_idx| match nvml.device_by_index(device_idx) {
-            Ok(device) => Some(device),
-            Err(_) => None,
-        })
-        .collect();
+    let profile = load_platform_profile();
+    let slot_map = profile.slot_map();
 
-    if nvml_devices.len() > 0 {
-        print_nvml_devices(&nvml_devices)?;
-        let nvml_devices: Vec<_> = nvml_devices
-            .into_iter()
-            .filter(|device| {
-                device
-                    .pci_info()
-                    .map_or(false, |info| info.slot().is_some())
-            })
-            .collect();
+    let mut sources: Vec<Box<dyn TemperatureSource>> = Vec::new();
+    match NvmlSource::new() {
+        Ok(nvml_source) => sources.push(Box::new(nvml_source)),
+        Err(err) => warn!("Couldn't initialize NVML, skipping GPU temperatures: {:?}", err),
+    }
+    sources.push(Box::new(HwmonSource::new()));
+    sources.push(Box::new(SmartSource::new()));
+
+    let devices = print_devices(&mut sources, &slot_map);
 
-        match OcsdContext::new(ocsd::client::base_address::ML350_GEN9) {
+    if devices.len() > 0 {
+        match OcsdContext::new(profile.base_address) {
             Ok(mut context) => {
                 let original_header = context.read_header();
                 debug!(
                     "Header data before write:\n{}",
                     format_struct_bytes(&original_header.to_bytes())
                 );
+                if !checksum::verify_checksum(&original_header.to_bytes()) {
+                    warn!("OCSD header checksum didn't validate; proceeding anyway");
+                }
+
+                if let Some(expected) = profile.max_option_cards {
+                    if expected != original_header.max_option_cards {
+                        warn!(
+                            "Platform profile {:?} expects {} option cards, but the live header reports {}",
+                            profile.name, expected, original_header.max_option_cards
+                        );
+                    }
+                }
 
                 let app_state = load_app_state(&original_header);
                 let count = Arc::new(atomic::AtomicU16::new(app_state.count));
 
-                let header = make_modified_header(&original_header, &app_state, &nvml_devices);
+                let available_slots: u8 = original_header
+                    .max_option_cards
+                    .saturating_sub(app_state.original_buffers_in_use);
+
+                if devices.len() > available_slots as usize {
+                    warn!(
+                        "Discovered {} device(s) but only {} option card slot(s) are available; the rest won't be reported",
+                        devices.len(),
+                        available_slots
+                    );
+                }
+                let initial_device_count = devices.len().min(available_slots as usize);
+
+                let header = make_modified_header(&original_header, &app_state, initial_device_count);
                 debug!(
                     "Writing header data:\n{}",
                     format_struct_bytes(&header.to_bytes())
@@ -301,19 +295,15 @@ fn main() -> Result<(), NvmlError> {
                 context.write_header(&header);
 
                 let should_exit = Arc::new(atomic::AtomicBool::new(false));
-                let mut file = open_state_file();
 
                 let should_exit_clone = should_exit.clone();
                 let count_clone = count.clone();
+                let original_buffers_in_use = app_state.original_buffers_in_use;
                 let _ = ctrlc::set_handler(move || {
-                    serde_json::to_writer(
-                        &mut file,
-                        &AppState {
-                            count: (*count_clone).load(atomic::Ordering::Relaxed),
-                            ..app_state
-                        },
-                    )
-                    .unwrap();
+                    save_app_state(
+                        count_clone.load(atomic::Ordering::Relaxed),
+                        original_buffers_in_use,
+                    );
                     should_exit_clone.store(true, atomic::Ordering::Relaxed);
                 });
 
@@ -322,10 +312,34 @@ fn main() -> Result<(), NvmlError> {
                     (index + app_state.original_buffers_in_use as usize).into()
                 }
 
+                /// Resolves the `device_mappings` index a device actually lands on. A
+                /// device whose PCI bus matched an entry in the platform's slot_map
+                /// config is written to that slot directly, so the config controls
+                /// real placement; everything else (no PCI bus, or no match) is
+                /// appended sequentially after the platform's pre-existing buffers,
+                /// skipping any slot `explicit_slots` says is already claimed by a
+                /// slot-mapped device this tick, same as before slot_map existed
+                /// otherwise.
+                fn ocsd_slot_for_device(
+                    device: &DeviceInfo,
+                    next_fallback_offset: &mut usize,
+                    app_state: &AppState,
+                    explicit_slots: &std::collections::HashSet<usize>,
+                ) -> usize {
+                    match device.slot {
+                        Some(slot) => slot as usize,
+                        None => loop {
+                            let slot = ocsd_slot_for_index(*next_fallback_offset, app_state);
+                            *next_fallback_offset += 1;
+                            if !explicit_slots.contains(&slot) {
+                                break slot;
+                            }
+                        },
+                    }
+                }
+
                 // Zero out option cards beyond the originals, in case we left something behind on previous run
-                for index in
-                    0..(original_header.max_option_cards - app_state.original_buffers_in_use)
-                {
+                for index in 0..available_slots {
                     context.device_mappings[ocsd_slot_for_index(index as usize, &app_state)].write(
                         &OcsdDevice {
                             header: OcsdDeviceHeader {
@@ -340,11 +354,66 @@ fn main() -> Result<(), NvmlError> {
                 }
 
                 loop {
-                    for (index, device) in nvml_devices.iter().enumerate() {
-                        let device_info =
-                            device.read_device_info((*count).load(atomic::Ordering::Relaxed))?;
-                        context.device_mappings[ocsd_slot_for_index(index, &app_state)]
-                            .write(&device_info.into());
+                    let current_count = (*count).load(atomic::Ordering::Relaxed);
+                    let mut tick_devices = Vec::new();
+                    for source in &mut sources {
+                        tick_devices.extend(source.read_devices(current_count, &slot_map));
+                    }
+
+                    let explicit_slots: std::collections::HashSet<usize> = tick_devices
+                        .iter()
+                        .filter_map(|device| device.slot.map(|slot| slot as usize))
+                        .collect();
+
+                    let mut next_fallback_offset = 0usize;
+                    let mut claimed_slots: std::collections::HashSet<usize> = std::collections::HashSet::new();
+                    for device_info in tick_devices {
+                        let slot = ocsd_slot_for_device(
+                            &device_info,
+                            &mut next_fallback_offset,
+                            &app_state,
+                            &explicit_slots,
+                        );
+                        if let Some(explicit_slot) = device_info.slot {
+                            if (explicit_slot as usize) < app_state.original_buffers_in_use as usize
+                            {
+                                warn!(
+                                    "{} resolved to OCSD slot {} via slot_map, but the platform reports {} pre-existing option card slot(s); skipping to avoid overwriting genuine data",
+                                    device_info.name, explicit_slot, app_state.original_buffers_in_use
+                                );
+                                continue;
+                            }
+                        }
+                        if slot >= original_header.max_option_cards as usize {
+                            warn!(
+                                "{} resolved to OCSD slot {} but the platform only has {} option card slot(s); skipping",
+                                device_info.name, slot, original_header.max_option_cards
+                            );
+                            continue;
+                        }
+                        if !claimed_slots.insert(slot) {
+                            warn!(
+                                "{} also resolved to OCSD slot {}, which another device already claimed this tick; skipping",
+                                device_info.name, slot
+                            );
+                            continue;
+                        }
+                        let device_name = device_info.name.clone();
+                        let ocsd_device = match OcsdDevice::try_from(device_info) {
+                            Ok(ocsd_device) => ocsd_device,
+                            Err(_) => {
+                                warn!(
+                                    "{} has a temperature reading out of OCSD's representable range; leaving slot {} unchanged this tick",
+                                    device_name, slot
+                                );
+                                continue;
+                            }
+                        };
+                        context.device_mappings[slot].write(&ocsd_device);
+                    }
+
+                    if current_count % STATE_SAVE_INTERVAL_TICKS == 0 {
+                        save_app_state(current_count, app_state.original_buffers_in_use);
                     }
 
                     std::thread::sleep(Duration::from_millis(1000));
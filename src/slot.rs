@@ -0,0 +1,7 @@
+use crate::config::SlotMap;
+
+pub trait HasSlot {
+    /// Looks up the OCSD option-card slot this PCI device occupies, per the
+    /// active platform's slot map.
+    fn slot(&self, slot_map: &SlotMap) -> Option<u8>;
+}
@@ -0,0 +1,220 @@
+use super::TemperatureSource;
+use crate::config::SlotMap;
+use crate::device::DeviceInfo;
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+const HWMON_ROOT: &str = "/sys/class/hwmon";
+const THERMAL_ROOT: &str = "/sys/class/thermal";
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_millidegree(path: &Path) -> Option<i16> {
+    read_trimmed(path)?.parse::<i64>().ok().map(|v| (v / 1000) as i16)
+}
+
+/// Recovers the PCI bus number of a hwmon chip by following its optional
+/// `device` symlink and looking for a `domain:bus:device.function` component
+/// (e.g. `0000:01:00.0`) anywhere along the resolved path.
+fn pci_bus_from_device_link(hwmon_dir: &Path) -> Option<u8> {
+    let target = fs::read_link(hwmon_dir.join("device")).ok()?;
+    target.components().find_map(|component| {
+        let part = component.as_os_str().to_str()?;
+        let fields: Vec<&str> = part.split(':').collect();
+        match fields.as_slice() {
+            [_domain, bus, _device_function] => u8::from_str_radix(bus, 16).ok(),
+            _ => None,
+        }
+    })
+}
+
+/// One `tempN_*` group exposed by a hwmon chip.
+struct HwmonTempChannel {
+    index: u32,
+}
+
+fn temp_channels(hwmon_dir: &Path) -> Vec<HwmonTempChannel> {
+    let mut indices: Vec<u32> = fs::read_dir(hwmon_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+            let name = file_name.to_str()?;
+            let suffix = name.strip_prefix("temp")?.strip_suffix("_input")?;
+            suffix.parse::<u32>().ok()
+        })
+        .collect();
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+        .into_iter()
+        .map(|index| HwmonTempChannel { index })
+        .collect()
+}
+
+fn read_hwmon_chip(
+    hwmon_dir: &Path,
+    slot_map: &SlotMap,
+    last_known: &HashMap<String, DeviceInfo>,
+) -> Vec<DeviceInfo> {
+    let chip_label = match read_trimmed(&hwmon_dir.join("name")) {
+        Some(label) => label,
+        None => return Vec::new(),
+    };
+    let pci_bus = pci_bus_from_device_link(hwmon_dir);
+    let slot = pci_bus.and_then(|bus| slot_map.slot_for_pci_bus(bus));
+
+    temp_channels(hwmon_dir)
+        .into_iter()
+        .filter_map(|channel| {
+            let label = read_trimmed(&hwmon_dir.join(format!("temp{}_label", channel.index)));
+            let name = match &label {
+                Some(label) => format!("{} {}", chip_label, label),
+                None => format!("{} temp{}", chip_label, channel.index),
+            };
+
+            let current_temperature_celsius = match read_millidegree(
+                &hwmon_dir.join(format!("temp{}_input", channel.index)),
+            ) {
+                Some(value) => value,
+                None => {
+                    warn!("Couldn't read {}, reusing last known reading", name);
+                    return last_known.get(&name).cloned().map(|mut stale| {
+                        stale.failed = true;
+                        stale
+                    });
+                }
+            };
+            let max = read_millidegree(&hwmon_dir.join(format!("temp{}_max", channel.index)));
+            let crit = read_millidegree(&hwmon_dir.join(format!("temp{}_crit", channel.index)));
+
+            Some(DeviceInfo {
+                slot,
+                pci_bus,
+                current_temperature_celsius,
+                temperature_threshold_celsius: max,
+                caution_threshold_celsius: crit,
+                name,
+                count: 0,
+                failed: false,
+            })
+        })
+        .collect()
+}
+
+fn scan_hwmon(slot_map: &SlotMap, last_known: &HashMap<String, DeviceInfo>) -> Vec<DeviceInfo> {
+    let entries = match fs::read_dir(HWMON_ROOT) {
+        Ok(entries) => entries,
+        Err(err) => {
+            debug!("Couldn't read {}: {:?}", HWMON_ROOT, err);
+            return Vec::new();
+        }
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .flat_map(|entry| read_hwmon_chip(&entry.path(), slot_map, last_known))
+        .collect()
+}
+
+fn scan_thermal_zones(last_known: &HashMap<String, DeviceInfo>) -> Vec<DeviceInfo> {
+    let entries = match fs::read_dir(THERMAL_ROOT) {
+        Ok(entries) => entries,
+        Err(err) => {
+            debug!("Couldn't read {}: {:?}", THERMAL_ROOT, err);
+            return Vec::new();
+        }
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .map_or(false, |name| name.starts_with("thermal_zone"))
+        })
+        .filter_map(|entry| {
+            let dir = entry.path();
+            let name = read_trimmed(&dir.join("type"))?;
+            let current_temperature_celsius = match read_millidegree(&dir.join("temp")) {
+                Some(value) => value,
+                None => {
+                    warn!("Couldn't read {}, reusing last known reading", name);
+                    return last_known.get(&name).cloned().map(|mut stale| {
+                        stale.failed = true;
+                        stale
+                    });
+                }
+            };
+            Some(DeviceInfo {
+                slot: None,
+                pci_bus: None,
+                current_temperature_celsius,
+                temperature_threshold_celsius: None,
+                caution_threshold_celsius: None,
+                name,
+                count: 0,
+                failed: false,
+            })
+        })
+        .collect()
+}
+
+/// Temperature source backed by Linux sysfs: `hwmon` chips (CPU package,
+/// chipset, VRM, etc.) plus `thermal_zone`s, for platform sensors (e.g.
+/// `acpitz`) that have no hwmon counterpart. A `thermal_zone` whose type
+/// matches the name of a hwmon chip that reported readings (or one of its
+/// full sensor names) is skipped.
+pub struct HwmonSource {
+    last_known: HashMap<String, DeviceInfo>,
+}
+
+impl HwmonSource {
+    pub fn new() -> Self {
+        Self {
+            last_known: HashMap::new(),
+        }
+    }
+}
+
+impl TemperatureSource for HwmonSource {
+    fn name(&self) -> &str {
+        "hwmon"
+    }
+
+    fn read_devices(&mut self, count: u16, slot_map: &SlotMap) -> Vec<DeviceInfo> {
+        let mut devices = scan_hwmon(slot_map, &self.last_known);
+
+        // hwmon sensor names are "<chip> <label>", so match the chip part too.
+        let seen_names: HashSet<String> = devices
+            .iter()
+            .flat_map(|d| {
+                let chip = d.name.split(' ').next().unwrap_or_default().to_string();
+                [d.name.clone(), chip]
+            })
+            .collect();
+        for device in scan_thermal_zones(&self.last_known) {
+            if !seen_names.contains(&device.name) {
+                devices.push(device);
+            }
+        }
+
+        for device in &mut devices {
+            device.count = count;
+        }
+
+        for device in &devices {
+            if !device.failed {
+                self.last_known.insert(device.name.clone(), device.clone());
+            }
+        }
+
+        devices
+    }
+}
@@ -0,0 +1,22 @@
+pub mod hwmon;
+pub mod nvml;
+pub mod smart;
+
+use crate::config::SlotMap;
+use crate::device::DeviceInfo;
+
+/// A source of temperature readings that can be surfaced into OCSD option
+/// cards, e.g. NVML-managed GPUs or sysfs-exposed hwmon/thermal sensors.
+pub trait TemperatureSource {
+    /// Human-readable name for this source, used in logging.
+    fn name(&self) -> &str;
+
+    /// Poll every device visible through this source. `count` is the current
+    /// OCSD update counter and is stamped onto each reading; `slot_map`
+    /// resolves a device's PCI bus to its OCSD option-card slot.
+    ///
+    /// A device that fails to read this tick is reported with its last known
+    /// reading and `failed` set, rather than being dropped — dropping it
+    /// would shift every device after it into the wrong OCSD slot.
+    fn read_devices(&mut self, count: u16, slot_map: &SlotMap) -> Vec<DeviceInfo>;
+}
@@ -0,0 +1,156 @@
+use super::TemperatureSource;
+use crate::config::SlotMap;
+use crate::device::DeviceInfo;
+use crate::slot::HasSlot;
+use log::warn;
+use nvml_wrapper::enum_wrappers::device::{TemperatureSensor, TemperatureThreshold};
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Nvml;
+
+trait ReadDeviceInfo {
+    fn read_device_info(&self, count: u16, slot_map: &SlotMap) -> Option<DeviceInfo>;
+}
+
+impl ReadDeviceInfo for nvml_wrapper::Device<'_> {
+    fn read_device_info(&self, count: u16, slot_map: &SlotMap) -> Option<DeviceInfo> {
+        let pci_info = match self.pci_info() {
+            Ok(pci_info) => pci_info,
+            Err(err) => {
+                warn!("Couldn't read PCI info: {:?}", err);
+                return None;
+            }
+        };
+        let slot = pci_info.slot(slot_map);
+        let pci_bus: u8 = match pci_info.bus.try_into() {
+            Ok(pci_bus) => pci_bus,
+            Err(_) => {
+                warn!("PCI bus {} is out of range", pci_info.bus);
+                return None;
+            }
+        };
+
+        let raw_temperature = match self.temperature(TemperatureSensor::Gpu) {
+            Ok(raw_temperature) => raw_temperature,
+            Err(err) => {
+                warn!("Couldn't read temperature: {:?}", err);
+                return None;
+            }
+        };
+        let current_temperature_celsius: i16 = match raw_temperature.try_into() {
+            Ok(temperature) => temperature,
+            Err(_) => {
+                warn!("Temperature {} is out of range", raw_temperature);
+                return None;
+            }
+        };
+
+        let temperature_threshold_celsius: Option<i16> =
+            match self.temperature_threshold(TemperatureThreshold::Slowdown) {
+                Ok(threshold) => match (threshold - 20).try_into() {
+                    Ok(threshold) => Some(threshold),
+                    Err(_) => {
+                        warn!("Temperature threshold {} is out of range", threshold);
+                        None
+                    }
+                },
+                Err(_) => Some(70),
+            };
+
+        let device_name = match self.name() {
+            Ok(device_name) => device_name,
+            Err(err) => {
+                warn!("Couldn't read device name: {:?}", err);
+                return None;
+            }
+        };
+
+        Some(DeviceInfo {
+            slot,
+            pci_bus: Some(pci_bus),
+            current_temperature_celsius,
+            // the below offset is required for correct temperatures
+            // to be displayed in iLO, but also prevents fans from spinning
+            // up until close to 90deg (which is generally not desirable)
+            // + (temperature_threshold_celsius.unwrap() - 90),
+            temperature_threshold_celsius,
+            caution_threshold_celsius: None,
+            name: device_name,
+            count,
+            failed: false,
+        })
+    }
+}
+
+/// Temperature source backed by NVIDIA's management library, reporting one
+/// reading per visible GPU.
+pub struct NvmlSource {
+    nvml: Nvml,
+    last_known: Vec<DeviceInfo>,
+}
+
+impl NvmlSource {
+    pub fn new() -> Result<Self, NvmlError> {
+        Ok(Self {
+            nvml: Nvml::init()?,
+            last_known: Vec::new(),
+        })
+    }
+
+    pub fn device_count(&self) -> Result<u32, NvmlError> {
+        self.nvml.device_count()
+    }
+}
+
+impl TemperatureSource for NvmlSource {
+    fn name(&self) -> &str {
+        "NVML"
+    }
+
+    fn read_devices(&mut self, count: u16, slot_map: &SlotMap) -> Vec<DeviceInfo> {
+        let num_devices = match self.nvml.device_count() {
+            Ok(num_devices) => num_devices,
+            Err(err) => {
+                warn!(
+                    "Couldn't enumerate NVML devices, reusing last known readings: {:?}",
+                    err
+                );
+                for device in &mut self.last_known {
+                    device.failed = true;
+                    device.count = count;
+                }
+                return self.last_known.clone();
+            }
+        };
+
+        let devices: Vec<DeviceInfo> = (0..num_devices)
+            .filter_map(|device_idx| {
+                let fresh = self
+                    .nvml
+                    .device_by_index(device_idx)
+                    .ok()
+                    .and_then(|device| device.read_device_info(count, slot_map));
+
+                match fresh {
+                    Some(device_info) => Some(device_info),
+                    None => {
+                        warn!(
+                            "Couldn't read NVML device {}, reusing last known reading",
+                            device_idx
+                        );
+                        self.last_known
+                            .get(device_idx as usize)
+                            .cloned()
+                            .map(|mut stale| {
+                                stale.failed = true;
+                                stale.count = count;
+                                stale
+                            })
+                    }
+                }
+            })
+            .collect();
+
+        self.last_known = devices.clone();
+        devices
+    }
+}
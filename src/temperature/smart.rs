@@ -0,0 +1,302 @@
+use super::TemperatureSource;
+use crate::config::SlotMap;
+use crate::device::DeviceInfo;
+use log::{debug, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Warn/critical temperature defaults for a class of storage device, used to
+/// populate `max_continuous_threshold`/`caution_threshold` for every drive of
+/// that class; drive-reported limits from `smartctl` are not read.
+#[derive(Debug, Clone, Copy)]
+pub struct SmartThresholds {
+    pub warn_celsius: i16,
+    pub critical_celsius: i16,
+}
+
+const SATA_DEFAULT_THRESHOLDS: SmartThresholds = SmartThresholds {
+    warn_celsius: 40,
+    critical_celsius: 45,
+};
+
+const NVME_DEFAULT_THRESHOLDS: SmartThresholds = SmartThresholds {
+    warn_celsius: 50,
+    critical_celsius: 60,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DriveKind {
+    Sata,
+    Nvme,
+}
+
+impl DriveKind {
+    fn default_thresholds(self) -> SmartThresholds {
+        match self {
+            DriveKind::Sata => SATA_DEFAULT_THRESHOLDS,
+            DriveKind::Nvme => NVME_DEFAULT_THRESHOLDS,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct SmartctlOutput {
+    smartctl: SmartctlSelfInfo,
+    temperature: Option<SmartTemperature>,
+    #[serde(default)]
+    power_on_time: Option<PowerOnTime>,
+    smart_status: Option<SmartStatus>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SmartctlSelfInfo {
+    exit_status: i32,
+    #[serde(default)]
+    messages: Vec<SmartctlMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SmartctlMessage {
+    string: String,
+    severity: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SmartTemperature {
+    current: i16,
+}
+
+#[derive(Deserialize, Debug)]
+struct PowerOnTime {
+    hours: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct SmartStatus {
+    passed: bool,
+}
+
+fn is_sata_disk_name(name: &str) -> bool {
+    match name.strip_prefix("sd") {
+        Some(rest) => !rest.is_empty() && rest.chars().all(|c| c.is_ascii_lowercase()),
+        None => false,
+    }
+}
+
+/// Matches a whole NVMe namespace device, e.g. `nvme0n1`, but not a
+/// controller (`nvme0`) or a partition (`nvme0n1p1`).
+fn is_nvme_namespace_name(name: &str) -> bool {
+    let rest = match name.strip_prefix("nvme") {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut chars = rest.chars().peekable();
+
+    let mut saw_controller_digit = false;
+    while matches!(chars.peek(), Some(c) if c.is_ascii_digit()) {
+        chars.next();
+        saw_controller_digit = true;
+    }
+    if !saw_controller_digit || chars.next() != Some('n') {
+        return false;
+    }
+
+    let mut saw_namespace_digit = false;
+    for c in chars {
+        if !c.is_ascii_digit() {
+            return false;
+        }
+        saw_namespace_digit = true;
+    }
+    saw_namespace_digit
+}
+
+fn discover_drives(dev_root: &Path) -> Vec<(PathBuf, DriveKind)> {
+    let entries = match fs::read_dir(dev_root) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("Couldn't read {}: {:?}", dev_root.display(), err);
+            return Vec::new();
+        }
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+            let name = file_name.to_str()?;
+            if is_sata_disk_name(name) {
+                Some((entry.path(), DriveKind::Sata))
+            } else if is_nvme_namespace_name(name) {
+                Some((entry.path(), DriveKind::Nvme))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn run_smartctl(device: &Path) -> Option<SmartctlOutput> {
+    let output = Command::new("smartctl")
+        .arg("--json")
+        .arg("-a")
+        .arg(device)
+        .output();
+
+    let output = match output {
+        Ok(output) => output,
+        Err(err) => {
+            warn!("Couldn't run smartctl on {}: {:?}", device.display(), err);
+            return None;
+        }
+    };
+
+    match serde_json::from_slice::<SmartctlOutput>(&output.stdout) {
+        Ok(parsed) => Some(parsed),
+        Err(err) => {
+            warn!(
+                "Couldn't parse smartctl output for {}: {:?}",
+                device.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+fn smart_failed(smartctl: &SmartctlOutput) -> bool {
+    let status_failed = smartctl
+        .smart_status
+        .as_ref()
+        .map_or(false, |status| !status.passed);
+    let has_error_message = smartctl
+        .smartctl
+        .messages
+        .iter()
+        .any(|message| message.severity == "error");
+
+    status_failed || smartctl.smartctl.exit_status != 0 || has_error_message
+}
+
+/// A drive reading, tagged with whether it came from a live `smartctl`
+/// invocation (`Fresh`) or was reused from `last_known` because this tick's
+/// invocation errored or returned no temperature (`Stale`). A `Stale`
+/// reading always has `DeviceInfo::failed` set; on a `Fresh` one, `failed`
+/// reflects the drive's own SMART health and can be true as well.
+enum DriveReading {
+    Fresh(DeviceInfo),
+    Stale(DeviceInfo),
+}
+
+impl DriveReading {
+    fn into_device_info(self) -> DeviceInfo {
+        match self {
+            DriveReading::Fresh(device) | DriveReading::Stale(device) => device,
+        }
+    }
+}
+
+fn read_drive(
+    device: &Path,
+    kind: DriveKind,
+    last_known: &HashMap<String, DeviceInfo>,
+) -> Option<DriveReading> {
+    let name = device
+        .file_name()
+        .map_or_else(|| device.display().to_string(), |n| n.to_string_lossy().into_owned());
+
+    let smartctl = match run_smartctl(device) {
+        Some(smartctl) => smartctl,
+        None => {
+            warn!("Reusing last known reading for {}", name);
+            return last_known.get(&name).cloned().map(|mut stale| {
+                stale.failed = true;
+                DriveReading::Stale(stale)
+            });
+        }
+    };
+    let current_temperature_celsius = match smartctl.temperature.as_ref() {
+        Some(temperature) => temperature.current,
+        None => {
+            warn!(
+                "smartctl output for {} had no temperature, reusing last known reading",
+                name
+            );
+            return last_known.get(&name).cloned().map(|mut stale| {
+                stale.failed = true;
+                DriveReading::Stale(stale)
+            });
+        }
+    };
+
+    if let Some(power_on_time) = &smartctl.power_on_time {
+        debug!(
+            "{} has been powered on for {} hours",
+            device.display(),
+            power_on_time.hours
+        );
+    }
+    for message in &smartctl.smartctl.messages {
+        warn!("smartctl on {}: {}", device.display(), message.string);
+    }
+
+    let thresholds = kind.default_thresholds();
+
+    Some(DriveReading::Fresh(DeviceInfo {
+        slot: None,
+        pci_bus: None,
+        current_temperature_celsius,
+        temperature_threshold_celsius: Some(thresholds.warn_celsius),
+        caution_threshold_celsius: Some(thresholds.critical_celsius),
+        name,
+        count: 0,
+        failed: smart_failed(&smartctl),
+    }))
+}
+
+/// Temperature source backed by `smartctl`, reporting one reading per SATA
+/// disk (`/dev/sd*`) or NVMe namespace (`/dev/nvme*`).
+pub struct SmartSource {
+    dev_root: PathBuf,
+    last_known: HashMap<String, DeviceInfo>,
+}
+
+impl SmartSource {
+    pub fn new() -> Self {
+        Self {
+            dev_root: PathBuf::from("/dev"),
+            last_known: HashMap::new(),
+        }
+    }
+}
+
+impl TemperatureSource for SmartSource {
+    fn name(&self) -> &str {
+        "smartctl"
+    }
+
+    fn read_devices(&mut self, count: u16, _slot_map: &SlotMap) -> Vec<DeviceInfo> {
+        let readings: Vec<DriveReading> = discover_drives(&self.dev_root)
+            .into_iter()
+            .filter_map(|(device, kind)| read_drive(&device, kind, &self.last_known))
+            .collect();
+
+        for reading in &readings {
+            if let DriveReading::Fresh(device) = reading {
+                self.last_known.insert(device.name.clone(), device.clone());
+            }
+        }
+
+        readings
+            .into_iter()
+            .map(DriveReading::into_device_info)
+            .map(|mut device_info| {
+                device_info.count = count;
+                device_info
+            })
+            .collect()
+    }
+}